@@ -1,6 +1,7 @@
 use serde::{Deserialize, Serialize};
 
 use de::Deserializer;
+use error::Error;
 use ser::Serializer;
 use token::Token;
 
@@ -77,6 +78,58 @@ where
     }
 }
 
+/// Asserts that `value` serializes to the given `tokens` when driven by a
+/// specific `version_map`.
+///
+/// This is the serialize-side counterpart to `assert_de_tokens_versions`: it
+/// pins down that serialization stamps the tokens it emits with the version
+/// number and field layout that `version_map` dictates, so a version bump can
+/// be verified on both the read and the write side.
+///
+/// ```edition2018
+/// # use serde::{Serialize, Deserialize};
+/// # use serde_test::{assert_ser_tokens_versions, Token};
+/// #
+/// # fn main() {
+/// #[derive(Serialize, Deserialize, PartialEq, Debug)]
+/// struct S {
+///     a: u8,
+///     b: u8,
+/// }
+///
+/// let s = S { a: 0, b: 0 };
+/// assert_ser_tokens_versions(&s, &[
+///     Token::StructStart("S", 2),
+///     Token::StructSep,
+///     Token::Str("a"),
+///     Token::U8(0),
+///     Token::StructSep,
+///     Token::Str("b"),
+///     Token::U8(0),
+///     Token::StructEnd,
+/// ],
+///     None);
+/// # }
+/// ```
+#[cfg(feature = "versioning")]
+pub fn assert_ser_tokens_versions<'de, T>(
+    value: &T,
+    tokens: &[Token],
+    version_map: Option<&'de serde::de::VersionMap>,
+) where
+    T: Serialize,
+{
+    let mut ser = Serializer::with_versions(tokens, version_map);
+    match value.serialize(&mut ser) {
+        Ok(_) => {}
+        Err(err) => panic!("value failed to serialize: {}", err),
+    }
+
+    if ser.remaining() > 0 {
+        panic!("{} remaining tokens", ser.remaining());
+    }
+}
+
 /// Asserts that `value` serializes to the given `tokens`, and then yields
 /// `error`.
 ///
@@ -129,6 +182,72 @@ where
     }
 }
 
+/// Asserts that `value` serializes to the given `tokens`, and then yields an
+/// error matching the given `predicate`.
+///
+/// Use this instead of `assert_ser_tokens_error` when the rendered error text
+/// is environment-dependent, such as the mutex-poison message in the
+/// `assert_ser_tokens_error` example above, which can vary across platforms
+/// and std versions.
+///
+/// ```edition2018
+/// use std::sync::{Arc, Mutex};
+/// use std::thread;
+///
+/// use serde::Serialize;
+/// use serde_test::{assert_ser_tokens_error_matches, Token};
+///
+/// #[derive(Serialize)]
+/// struct Example {
+///     lock: Arc<Mutex<u32>>,
+/// }
+///
+/// fn main() {
+///     let example = Example { lock: Arc::new(Mutex::new(0)) };
+///     let lock = example.lock.clone();
+///
+///     let _ = thread::spawn(move || {
+///         // This thread will acquire the mutex first, unwrapping the result
+///         // of `lock` because the lock has not been poisoned.
+///         let _guard = lock.lock().unwrap();
+///
+///         // This panic while holding the lock (`_guard` is in scope) will
+///         // poison the mutex.
+///         panic!()
+///     }).join();
+///
+///     let expected = &[
+///         Token::Struct { name: "Example", len: 1 },
+///         Token::Str("lock"),
+///     ];
+///     assert_ser_tokens_error_matches(
+///         &example,
+///         expected,
+///         |error| error.starts_with("lock poison error"),
+///     );
+/// }
+/// ```
+pub fn assert_ser_tokens_error_matches<T, F>(value: &T, tokens: &[Token], predicate: F)
+where
+    T: Serialize,
+    F: FnOnce(&str) -> bool,
+{
+    let mut ser = Serializer::new(tokens);
+    match value.serialize(&mut ser) {
+        Ok(_) => panic!("value serialized successfully"),
+        Err(e) => {
+            let message = e.to_string();
+            if !predicate(&message) {
+                panic!("unexpected error: {}", message);
+            }
+        }
+    }
+
+    if ser.remaining() > 0 {
+        panic!("{} remaining tokens", ser.remaining());
+    }
+}
+
 /// Asserts that the given `tokens` deserialize into `value`.
 ///
 /// ```edition2018
@@ -199,6 +318,199 @@ pub fn assert_de_tokens_versions<'de, T>(
     internal_assert_de_tokens(value, Deserializer::with_versions(tokens, version_map));
 }
 
+/// Runs a full serialize/deserialize round-trip through a version migration.
+///
+/// `old_tokens` is decoded under `version_map` and checked against `value`,
+/// exercising both `Deserialize::deserialize` and
+/// `Deserialize::deserialize_in_place`, the same way `assert_de_tokens` does
+/// for a single version. `value` is then serialized and checked against
+/// `new_tokens`, the encoding of the current version. This mirrors a forward
+/// migration: an old on-disk representation is decoded into the latest
+/// struct, and that latest struct is what gets re-emitted, so a single test
+/// proves a version bump both reads legacy data and writes the new layout.
+///
+/// ```edition2018
+/// # use serde::{Serialize, Deserialize};
+/// # use serde_test::{assert_tokens_versions, Token};
+/// #
+/// # fn main() {
+/// #[derive(Serialize, Deserialize, PartialEq, Debug)]
+/// struct S {
+///     a: u8,
+///     b: u8,
+/// }
+///
+/// let s = S { a: 0, b: 0 };
+/// let old_tokens = &[
+///     Token::StructStart("S", 2),
+///     Token::StructSep,
+///     Token::Str("a"),
+///     Token::U8(0),
+///     Token::StructSep,
+///     Token::Str("b"),
+///     Token::U8(0),
+///     Token::StructEnd,
+/// ];
+/// let new_tokens = old_tokens;
+/// assert_tokens_versions(&s, old_tokens, new_tokens, None);
+/// # }
+/// ```
+#[cfg(feature = "versioning")]
+pub fn assert_tokens_versions<'de, T>(
+    value: &T,
+    old_tokens: &'de [Token],
+    new_tokens: &[Token],
+    version_map: Option<&'de serde::de::VersionMap>,
+) where
+    T: Serialize + Deserialize<'de> + PartialEq + Debug,
+{
+    let in_place = internal_assert_de_tokens(value, Deserializer::with_versions(old_tokens, version_map));
+    internal_assert_de_in_place_tokens(
+        value,
+        in_place,
+        Deserializer::with_versions(old_tokens, version_map),
+    );
+
+    let mut ser = Serializer::with_versions(new_tokens, version_map);
+    match value.serialize(&mut ser) {
+        Ok(_) => {}
+        Err(err) => panic!("value failed to serialize: {}", err),
+    }
+    if ser.remaining() > 0 {
+        panic!("{} remaining tokens", ser.remaining());
+    }
+}
+
+/// Runs both `assert_ser_tokens` and `assert_de_tokens_eq_by`, using `cmp`
+/// instead of `PartialEq` to compare the deserialized result.
+///
+/// This is the analogue of `assert_tokens` for types whose equality is
+/// semantic rather than structural, such as those wrapping key material or
+/// arena handles, which commonly round-trip through a format like CBOR but
+/// don't derive a meaningful `PartialEq`. Serialization has no result to
+/// compare, so only the deserialize side needs the comparator.
+///
+/// ```edition2018
+/// # use serde::{Serialize, Deserialize};
+/// # use serde_test::{assert_tokens_eq_by, Token};
+/// #
+/// #[derive(Serialize, Deserialize, Debug)]
+/// struct Key(Vec<u8>);
+///
+/// # fn main() {
+/// assert_tokens_eq_by(
+///     &Key(vec![1, 2, 3]),
+///     &[
+///         Token::StructNewType("Key"),
+///         Token::SeqStart(Some(3)),
+///         Token::SeqSep,
+///         Token::U8(1),
+///         Token::SeqSep,
+///         Token::U8(2),
+///         Token::SeqSep,
+///         Token::U8(3),
+///         Token::SeqEnd,
+///     ],
+///     |a, b| a.0 == b.0,
+/// );
+/// # }
+/// ```
+pub fn assert_tokens_eq_by<'de, T, F>(value: &T, tokens: &'de [Token], cmp: F)
+where
+    T: Serialize + Deserialize<'de> + Debug,
+    F: Fn(&T, &T) -> bool,
+{
+    assert_ser_tokens(value, tokens);
+    assert_de_tokens_eq_by(value, tokens, cmp);
+}
+
+/// Asserts that the given `tokens` deserialize into `value`, using `cmp`
+/// instead of `PartialEq` to compare the result.
+///
+/// Useful for types whose equality is semantic rather than structural, such
+/// as those wrapping key material or arena handles, which commonly round-trip
+/// through a format like CBOR but don't derive a meaningful `PartialEq`.
+///
+/// ```edition2018
+/// # use serde::Deserialize;
+/// # use serde_test::{assert_de_tokens_eq_by, Token};
+/// #
+/// #[derive(Deserialize, Debug)]
+/// struct Key(Vec<u8>);
+///
+/// # fn main() {
+/// assert_de_tokens_eq_by(
+///     &Key(vec![1, 2, 3]),
+///     &[
+///         Token::StructNewType("Key"),
+///         Token::SeqStart(Some(3)),
+///         Token::SeqSep,
+///         Token::U8(1),
+///         Token::SeqSep,
+///         Token::U8(2),
+///         Token::SeqSep,
+///         Token::U8(3),
+///         Token::SeqEnd,
+///     ],
+///     |a, b| a.0 == b.0,
+/// );
+/// # }
+/// ```
+pub fn assert_de_tokens_eq_by<'de, T, F>(value: &T, tokens: &'de [Token], cmp: F)
+where
+    T: Deserialize<'de> + Debug,
+    F: Fn(&T, &T) -> bool,
+{
+    let in_place = internal_assert_de_tokens_by(value, Deserializer::new(tokens), &cmp);
+    internal_assert_de_in_place_tokens_by(value, in_place, Deserializer::new(tokens), &cmp);
+}
+
+fn internal_assert_de_tokens_by<'de, T, F>(value: &T, mut de: Deserializer<'de>, cmp: &F) -> T
+where
+    T: Deserialize<'de> + Debug,
+    F: Fn(&T, &T) -> bool,
+{
+    let result = match T::deserialize(&mut de) {
+        Ok(v) => {
+            if !cmp(&v, value) {
+                panic!("{:?} does not equal {:?} by the given comparator", v, value);
+            }
+            v
+        }
+        Err(e) => panic!("tokens failed to deserialize: {}", e),
+    };
+    if let Err(e) = de.end() {
+        panic!("{}", e);
+    }
+
+    result
+}
+
+fn internal_assert_de_in_place_tokens_by<'de, T, F>(
+    value: &T,
+    mut in_place: T,
+    mut de: Deserializer<'de>,
+    cmp: &F,
+) where
+    T: Deserialize<'de> + Debug,
+    F: Fn(&T, &T) -> bool,
+{
+    match T::deserialize_in_place(&mut de, &mut in_place) {
+        Ok(()) => {
+            if !cmp(&in_place, value) {
+                panic!(
+                    "{:?} does not equal {:?} by the given comparator",
+                    in_place, value
+                );
+            }
+        }
+        Err(e) => panic!("tokens failed to deserialize_in_place: {}", e),
+    }
+    if let Err(e) = de.end() {
+        panic!("{}", e);
+    }
+}
+
 fn internal_assert_de_tokens<'de, T>(value: &T, mut de: Deserializer<'de>) -> T
 where
     T: Deserialize<'de> + PartialEq + Debug,
@@ -210,8 +522,8 @@ where
         }
         Err(e) => panic!("tokens failed to deserialize: {}", e),
     };
-    if de.remaining() > 0 {
-        panic!("{} remaining tokens", de.remaining());
+    if let Err(e) = de.end() {
+        panic!("{}", e);
     }
 
     result
@@ -230,8 +542,8 @@ where
         }
         Err(e) => panic!("tokens failed to deserialize_in_place: {}", e),
     }
-    if de.remaining() > 0 {
-        panic!("{} remaining tokens", de.remaining());
+    if let Err(e) = de.end() {
+        panic!("{}", e);
     }
 }
 
@@ -259,13 +571,100 @@ where
 /// # }
 /// ```
 pub fn assert_de_tokens_error<'de, T>(tokens: &'de [Token], error: &str)
+where
+    T: Deserialize<'de>,
+{
+    let e = de_tokens_error::<T>(tokens);
+    assert_eq!(e, *error);
+}
+
+/// Deserializes `tokens` into `T`, panicking if deserialization succeeds, and
+/// returns the `Error` it produced.
+///
+/// Unlike `assert_de_tokens_error`, which only checks the error's `Display`
+/// output, this lets a caller `match` on one of this crate's own structured
+/// variants, such as `Error::UnexpectedToken` or `Error::InvalidName`,
+/// instead of parsing the rendered message. It does not help for an error
+/// that originates in a `#[derive(Deserialize)]` impl, though: unknown
+/// field, duplicate field, and other derive-generated failures all go
+/// through `de::Error::custom` and collapse into `Error::Message(String)`
+/// with no field name, index, or other payload to inspect separately from
+/// the message, as the `error.to_string()` fallback below demonstrates.
+/// `assert_de_tokens_error` is a thin wrapper over this function.
+///
+/// ```edition2018
+/// # use serde::Deserialize;
+/// # use serde_test::{de_tokens_error, Token};
+/// #
+/// # #[derive(Deserialize, Debug)]
+/// # #[serde(deny_unknown_fields)]
+/// # struct S { a: u8, b: u8 }
+/// #
+/// # fn main() {
+/// let error = de_tokens_error::<S>(&[
+///     Token::StructStart("S", 2),
+///     Token::StructSep,
+///     Token::Str("x"),
+/// ]);
+/// assert_eq!(error.to_string(), "unknown field `x`, expected `a` or `b`");
+/// # }
+/// ```
+pub fn de_tokens_error<'de, T>(tokens: &'de [Token]) -> Error
 where
     T: Deserialize<'de>,
+{
+    let mut de = Deserializer::new(tokens);
+    let error = match T::deserialize(&mut de) {
+        Ok(_) => panic!("tokens deserialized successfully"),
+        Err(e) => e,
+    };
+
+    // There may be one token left if a peek caused the error
+    de.next_token_opt();
+
+    if de.remaining() > 0 {
+        panic!("{} remaining tokens", de.remaining());
+    }
+
+    error
+}
+
+/// Asserts that the given `tokens` yield an error matching the given
+/// `predicate` when deserializing.
+///
+/// Use this instead of `assert_de_tokens_error` when the rendered error text
+/// is environment-dependent, such as the mutex-poison message that can vary
+/// across platforms and std versions.
+///
+/// ```edition2018
+/// # use serde::Deserialize;
+/// # use serde_test::{assert_de_tokens_error_matches, Token};
+/// #
+/// # #[derive(Deserialize)]
+/// # #[serde(deny_unknown_fields)]
+/// # struct S { a: u8 }
+/// #
+/// # fn main() {
+/// assert_de_tokens_error_matches::<S>(
+///     &[Token::StructStart("S", 1), Token::StructSep, Token::Str("x")],
+///     |error| error.starts_with("unknown field"),
+/// );
+/// # }
+/// ```
+pub fn assert_de_tokens_error_matches<'de, T, F>(tokens: &'de [Token], predicate: F)
+where
+    T: Deserialize<'de>,
+    F: FnOnce(&str) -> bool,
 {
     let mut de = Deserializer::new(tokens);
     match T::deserialize(&mut de) {
         Ok(_) => panic!("tokens deserialized successfully"),
-        Err(e) => assert_eq!(e, *error),
+        Err(e) => {
+            let message = e.to_string();
+            if !predicate(&message) {
+                panic!("unexpected error: {}", message);
+            }
+        }
     }
 
     // There may be one token left if a peek caused the error
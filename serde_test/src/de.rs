@@ -1,3 +1,5 @@
+use std::mem;
+
 use serde::de::{self, Deserialize, DeserializeSeed, EnumVisitor, MapVisitor, SeqVisitor,
                 VariantVisitor, Visitor};
 use serde::de::value::{ValueDeserializer, MapVisitorDeserializer, SeqVisitorDeserializer};
@@ -5,24 +7,238 @@ use serde::de::value::{ValueDeserializer, MapVisitorDeserializer, SeqVisitorDese
 use error::Error;
 use token::Token;
 
-/// A `Deserializer` that reads from a list of tokens.
-pub struct Deserializer<'de> {
-    tokens: &'de [Token],
+/// Widens an IEEE 754 half-precision float, stored as its raw bits, to an
+/// `f32`, mirroring how a format like CBOR decodes a half float before
+/// handing it to the visitor.
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = (bits >> 15) as u32;
+    let exponent = ((bits >> 10) & 0x1f) as u32;
+    let mantissa = (bits & 0x3ff) as u32;
+
+    let sign = sign << 31;
+
+    if exponent == 0 {
+        if mantissa == 0 {
+            return f32::from_bits(sign);
+        }
+        // Subnormal half float: normalize the mantissa by shifting until its
+        // implicit leading bit lands, adjusting the exponent to match.
+        let mut exponent = -1i32;
+        let mut mantissa = mantissa;
+        loop {
+            mantissa <<= 1;
+            exponent += 1;
+            if mantissa & 0x400 != 0 {
+                break;
+            }
+        }
+        let mantissa = (mantissa & 0x3ff) << 13;
+        let exponent = ((127 - 15 - exponent) as u32) << 23;
+        return f32::from_bits(sign | exponent | mantissa);
+    } else if exponent == 0x1f {
+        // Infinity or NaN.
+        return f32::from_bits(sign | (0xff << 23) | (mantissa << 13));
+    }
+
+    let exponent = (exponent + (127 - 15)) << 23;
+    f32::from_bits(sign | exponent | (mantissa << 13))
+}
+
+/// A source of `Token`s that a `Deserializer` can read from.
+///
+/// This is implemented for `&'de [Token]`, the common case of a fully
+/// materialized token list, and for any `Iterator<Item = Token>` via
+/// `IterTokenSource`, so the test deserializer can also be driven off of
+/// lazily-generated or incrementally-produced token streams.
+pub trait TokenSource<'de> {
+    /// Returns the next token without consuming it.
+    fn peek(&self) -> Option<&Token>;
+
+    /// Consumes and returns the next token.
+    fn next(&mut self) -> Option<Token>;
+
+    /// The number of tokens not yet consumed.
+    ///
+    /// This is exact for a source like `&'de [Token]` that knows its own
+    /// length, but an implementation backed by an arbitrary `Iterator` can
+    /// only report a lower bound, since `Iterator::size_hint`'s lower bound
+    /// is frequently `0`. Callers that need to reliably detect leftover
+    /// tokens, rather than just report a count for a panic message, should
+    /// use `peek()` (or `Deserializer::end`) instead of comparing this value
+    /// against zero.
+    fn remaining(&self) -> usize;
+}
+
+impl<'de> TokenSource<'de> for &'de [Token] {
+    fn peek(&self) -> Option<&Token> {
+        self.first()
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        if let Some((&first, rest)) = self.split_first() {
+            *self = rest;
+            Some(first)
+        } else {
+            None
+        }
+    }
+
+    fn remaining(&self) -> usize {
+        self.len()
+    }
+}
+
+/// Adapts any `Iterator<Item = Token>` into a `TokenSource`.
+///
+/// One token of lookahead is buffered eagerly so that `peek` can hand out a
+/// reference without needing `&mut self`.
+pub struct IterTokenSource<I>
+    where I: Iterator<Item = Token>
+{
+    iter: I,
+    peeked: Option<Token>,
+}
+
+impl<I> IterTokenSource<I>
+    where I: Iterator<Item = Token>
+{
+    /// Wraps `iter`, pulling the first token off of it immediately.
+    pub fn new(mut iter: I) -> Self {
+        let peeked = iter.next();
+        IterTokenSource {
+            iter: iter,
+            peeked: peeked,
+        }
+    }
+}
+
+impl<'de, I> TokenSource<'de> for IterTokenSource<I>
+    where I: Iterator<Item = Token>
+{
+    fn peek(&self) -> Option<&Token> {
+        self.peeked.as_ref()
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let next = self.iter.next();
+        mem::replace(&mut self.peeked, next)
+    }
+
+    /// A lower bound, not an exact count: `self.iter`'s `size_hint` lower
+    /// bound is frequently `0` for iterators that don't know their length
+    /// up front, so this can under-report how many tokens are actually
+    /// left. Use `peek()` to check whether the stream is exhausted.
+    fn remaining(&self) -> usize {
+        self.peeked.iter().count() + self.iter.size_hint().0
+    }
+}
+
+/// A `Deserializer` that reads from a `TokenSource`, by default a plain
+/// `&'de [Token]`.
+pub struct Deserializer<'de, S = &'de [Token]>
+    where S: TokenSource<'de>
+{
+    tokens: S,
+    human_readable: bool,
+    #[cfg(feature = "versioning")]
+    version_map: Option<&'de serde::de::VersionMap>,
 }
 
 impl<'de> Deserializer<'de> {
-    /// Creates the deserializer.
+    /// Creates the deserializer from a slice of tokens, in human-readable
+    /// mode. This is an alias for `Deserializer::readable`.
     pub fn new(tokens: &'de [Token]) -> Self {
-        Deserializer { tokens: tokens }
+        Deserializer::readable(tokens)
+    }
+
+    /// Creates the deserializer in human-readable mode, the mode text
+    /// formats like JSON use, so that `Deserialize` impls that branch on
+    /// `is_human_readable` take their human-readable path.
+    pub fn readable(tokens: &'de [Token]) -> Self {
+        Deserializer::from_source_readable(tokens, true)
+    }
+
+    /// Creates the deserializer in compact mode, the mode binary formats
+    /// like CBOR use, so that `Deserialize` impls that branch on
+    /// `is_human_readable` take their binary path.
+    pub fn compact(tokens: &'de [Token]) -> Self {
+        Deserializer::from_source_readable(tokens, false)
+    }
+
+    /// Creates the deserializer, driving it with the given `version_map` so
+    /// that a `Deserialize` impl can migrate an older on-disk representation
+    /// forward to the latest one.
+    #[cfg(feature = "versioning")]
+    pub fn with_versions(tokens: &'de [Token],
+                          version_map: Option<&'de serde::de::VersionMap>)
+                          -> Self {
+        Deserializer {
+            tokens: tokens,
+            human_readable: true,
+            version_map: version_map,
+        }
+    }
+
+    /// The version map this deserializer was constructed with, if any.
+    #[cfg(feature = "versioning")]
+    pub fn version_map(&self) -> Option<&'de serde::de::VersionMap> {
+        self.version_map
+    }
+}
+
+impl<'de, S> Deserializer<'de, S>
+    where S: TokenSource<'de>
+{
+    /// Creates the deserializer from any `TokenSource`, such as an
+    /// `IterTokenSource` wrapping a lazily-generated iterator of tokens, in
+    /// human-readable mode.
+    pub fn from_source(source: S) -> Self {
+        Deserializer::from_source_readable(source, true)
+    }
+
+    /// Creates the deserializer from any `TokenSource`, overriding
+    /// `is_human_readable` with `human_readable`.
+    pub fn from_source_readable(source: S, human_readable: bool) -> Self {
+        Deserializer {
+            tokens: source,
+            human_readable: human_readable,
+            #[cfg(feature = "versioning")]
+            version_map: None,
+        }
+    }
+
+    /// The number of tokens not yet consumed by deserialization.
+    ///
+    /// Exact when `S` is `&'de [Token]`. For a `TokenSource` backed by an
+    /// arbitrary iterator, this may only be a lower bound; see
+    /// `TokenSource::remaining`.
+    pub fn remaining(&self) -> usize {
+        self.tokens.remaining()
     }
 
     /// Pulls the next token off of the deserializer, ignoring it.
     pub fn next_token(&mut self) -> Option<Token> {
-        if let Some((&first, rest)) = self.tokens.split_first() {
-            self.tokens = rest;
-            Some(first)
-        } else {
-            None
+        self.tokens.next()
+    }
+
+    /// Pulls the next token off of the deserializer, ignoring it and its
+    /// absence. Used after an error to consume a token that may have only
+    /// been peeked at.
+    pub fn next_token_opt(&mut self) {
+        self.next_token();
+    }
+
+    /// Asserts that the token stream has been fully consumed, returning
+    /// `Error::TrailingTokens` carrying the first leftover token otherwise.
+    ///
+    /// Catches a `Deserialize` impl that stops reading early, such as one
+    /// that forgets to consume a trailing element or a nested `SeqEnd`,
+    /// which would otherwise silently pass a test that only checks the
+    /// produced value.
+    pub fn end(&mut self) -> Result<(), Error> {
+        match self.tokens.peek() {
+            Some(&token) => Err(Error::TrailingTokens(token)),
+            None => Ok(()),
         }
     }
 
@@ -77,7 +293,9 @@ impl<'de> Deserializer<'de> {
     }
 }
 
-impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
+impl<'de, 'a, S> de::Deserializer<'de> for &'a mut Deserializer<'de, S>
+    where S: TokenSource<'de>
+{
     type Error = Error;
 
     forward_to_deserialize! {
@@ -85,6 +303,15 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         seq bytes byte_buf map struct_field ignored_any
     }
 
+    fn is_human_readable(&self) -> bool {
+        self.human_readable
+    }
+
+    #[cfg(feature = "versioning")]
+    fn version_map(&self) -> Option<&serde::de::VersionMap> {
+        self.version_map
+    }
+
     fn deserialize<V>(self, visitor: V) -> Result<V::Value, Error>
         where V: Visitor<'de>
     {
@@ -100,6 +327,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
             Some(Token::U64(v)) => visitor.visit_u64(v),
             Some(Token::F32(v)) => visitor.visit_f32(v),
             Some(Token::F64(v)) => visitor.visit_f64(v),
+            Some(Token::F16Bits(v)) => visitor.visit_f32(f16_to_f32(v)),
             Some(Token::Char(v)) => visitor.visit_char(v),
             Some(Token::Str(v)) => visitor.visit_str(v),
             Some(Token::BorrowedStr(v)) => visitor.visit_borrowed_str(v),
@@ -149,7 +377,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Error>
         where V: Visitor<'de>
     {
-        match self.tokens.first() {
+        match self.tokens.peek() {
             Some(&Token::Unit) |
             Some(&Token::Option(false)) => {
                 self.next_token();
@@ -171,7 +399,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
                            -> Result<V::Value, Error>
         where V: Visitor<'de>
     {
-        match self.tokens.first() {
+        match self.tokens.peek() {
             Some(&Token::EnumStart(n)) if name == n => {
                 self.next_token();
 
@@ -194,7 +422,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     fn deserialize_unit_struct<V>(self, name: &str, visitor: V) -> Result<V::Value, Error>
         where V: Visitor<'de>
     {
-        match self.tokens.first() {
+        match self.tokens.peek() {
             Some(&Token::UnitStruct(n)) => {
                 self.next_token();
                 if name == n {
@@ -211,7 +439,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     fn deserialize_newtype_struct<V>(self, name: &str, visitor: V) -> Result<V::Value, Error>
         where V: Visitor<'de>
     {
-        match self.tokens.first() {
+        match self.tokens.peek() {
             Some(&Token::StructNewType(n)) => {
                 self.next_token();
                 if name == n {
@@ -228,7 +456,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     fn deserialize_seq_fixed_size<V>(self, len: usize, visitor: V) -> Result<V::Value, Error>
         where V: Visitor<'de>
     {
-        match self.tokens.first() {
+        match self.tokens.peek() {
             Some(&Token::SeqArrayStart(_)) => {
                 self.next_token();
                 self.visit_seq(Some(len), Token::SeqSep, Token::SeqEnd, visitor)
@@ -241,7 +469,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value, Error>
         where V: Visitor<'de>
     {
-        match self.tokens.first() {
+        match self.tokens.peek() {
             Some(&Token::Unit) |
             Some(&Token::UnitStruct(_)) => {
                 self.next_token();
@@ -278,7 +506,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
                                    -> Result<V::Value, Error>
         where V: Visitor<'de>
     {
-        match self.tokens.first() {
+        match self.tokens.peek() {
             Some(&Token::Unit) => {
                 self.next_token();
                 visitor.visit_unit()
@@ -319,6 +547,19 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         }
     }
 
+    /// A `Token::MapStart` is accepted here in addition to the dedicated
+    /// `Token::StructStart`, so a test can model a format like nu-json's
+    /// `preserve_order` mode, where map-shaped input arrives with its
+    /// entries in arbitrary key order. This isn't a new tolerance this
+    /// method introduces: `DeserializerMapVisitor`, the same visitor the
+    /// `StructStart` arm uses, hands entries over in stream order, and the
+    /// `#[derive(Deserialize)]`-generated visitor matches each key against
+    /// `fields` by name, so out-of-order fields already deserialize
+    /// identically to fields in declared order. A repeated key is rejected by the
+    /// `#[derive(Deserialize)]`-generated visitor with a "duplicate field"
+    /// error, not resolved last-wins. The `fields.len()` hint isn't passed
+    /// through for the `MapStart` case, since a stream with duplicates or
+    /// gaps may not actually contain exactly that many entries.
     fn deserialize_struct<V>(self,
                              name: &str,
                              fields: &'static [&'static str],
@@ -326,7 +567,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
                              -> Result<V::Value, Error>
         where V: Visitor<'de>
     {
-        match self.tokens.first() {
+        match self.tokens.peek() {
             Some(&Token::StructStart(n, _)) => {
                 self.next_token();
                 if name == n {
@@ -340,7 +581,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
             }
             Some(&Token::MapStart(_)) => {
                 self.next_token();
-                self.visit_map(Some(fields.len()), Token::MapSep, Token::MapEnd, visitor)
+                self.visit_map(None, Token::MapSep, Token::MapEnd, visitor)
             }
             Some(_) => self.deserialize(visitor),
             None => Err(Error::EndOfTokens),
@@ -350,20 +591,24 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
 
 //////////////////////////////////////////////////////////////////////////
 
-struct DeserializerSeqVisitor<'a, 'de: 'a> {
-    de: &'a mut Deserializer<'de>,
+struct DeserializerSeqVisitor<'a, 'de: 'a, S: 'a>
+    where S: TokenSource<'de>
+{
+    de: &'a mut Deserializer<'de, S>,
     len: Option<usize>,
     sep: Token,
     end: Token,
 }
 
-impl<'de, 'a> SeqVisitor<'de> for DeserializerSeqVisitor<'a, 'de> {
+impl<'de, 'a, S> SeqVisitor<'de> for DeserializerSeqVisitor<'a, 'de, S>
+    where S: TokenSource<'de>
+{
     type Error = Error;
 
     fn visit_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Error>
         where T: DeserializeSeed<'de>
     {
-        if self.de.tokens.first() == Some(&self.end) {
+        if self.de.tokens.peek() == Some(&self.end) {
             return Ok(None);
         }
         match self.de.next_token() {
@@ -384,20 +629,24 @@ impl<'de, 'a> SeqVisitor<'de> for DeserializerSeqVisitor<'a, 'de> {
 
 //////////////////////////////////////////////////////////////////////////
 
-struct DeserializerMapVisitor<'a, 'de: 'a> {
-    de: &'a mut Deserializer<'de>,
+struct DeserializerMapVisitor<'a, 'de: 'a, S: 'a>
+    where S: TokenSource<'de>
+{
+    de: &'a mut Deserializer<'de, S>,
     len: Option<usize>,
     sep: Token,
     end: Token,
 }
 
-impl<'de, 'a> MapVisitor<'de> for DeserializerMapVisitor<'a, 'de> {
+impl<'de, 'a, S> MapVisitor<'de> for DeserializerMapVisitor<'a, 'de, S>
+    where S: TokenSource<'de>
+{
     type Error = Error;
 
     fn visit_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Error>
         where K: DeserializeSeed<'de>
     {
-        if self.de.tokens.first() == Some(&self.end) {
+        if self.de.tokens.peek() == Some(&self.end) {
             return Ok(None);
         }
         match self.de.next_token() {
@@ -424,18 +673,22 @@ impl<'de, 'a> MapVisitor<'de> for DeserializerMapVisitor<'a, 'de> {
 
 //////////////////////////////////////////////////////////////////////////
 
-struct DeserializerEnumVisitor<'a, 'de: 'a> {
-    de: &'a mut Deserializer<'de>,
+struct DeserializerEnumVisitor<'a, 'de: 'a, S: 'a>
+    where S: TokenSource<'de>
+{
+    de: &'a mut Deserializer<'de, S>,
 }
 
-impl<'de, 'a> EnumVisitor<'de> for DeserializerEnumVisitor<'a, 'de> {
+impl<'de, 'a, S> EnumVisitor<'de> for DeserializerEnumVisitor<'a, 'de, S>
+    where S: TokenSource<'de>
+{
     type Error = Error;
     type Variant = Self;
 
     fn visit_variant_seed<V>(self, seed: V) -> Result<(V::Value, Self), Error>
         where V: DeserializeSeed<'de>
     {
-        match self.de.tokens.first() {
+        match self.de.tokens.peek() {
             Some(&Token::EnumUnit(_, v)) |
             Some(&Token::EnumNewType(_, v)) |
             Some(&Token::EnumSeqStart(_, v, _)) |
@@ -453,11 +706,13 @@ impl<'de, 'a> EnumVisitor<'de> for DeserializerEnumVisitor<'a, 'de> {
     }
 }
 
-impl<'de, 'a> VariantVisitor<'de> for DeserializerEnumVisitor<'a, 'de> {
+impl<'de, 'a, S> VariantVisitor<'de> for DeserializerEnumVisitor<'a, 'de, S>
+    where S: TokenSource<'de>
+{
     type Error = Error;
 
     fn visit_unit(self) -> Result<(), Error> {
-        match self.de.tokens.first() {
+        match self.de.tokens.peek() {
             Some(&Token::EnumUnit(_, _)) => {
                 self.de.next_token();
                 Ok(())
@@ -470,7 +725,7 @@ impl<'de, 'a> VariantVisitor<'de> for DeserializerEnumVisitor<'a, 'de> {
     fn visit_newtype_seed<T>(self, seed: T) -> Result<T::Value, Self::Error>
         where T: DeserializeSeed<'de>
     {
-        match self.de.tokens.first() {
+        match self.de.tokens.peek() {
             Some(&Token::EnumNewType(_, _)) => {
                 self.de.next_token();
                 seed.deserialize(self.de)
@@ -483,7 +738,7 @@ impl<'de, 'a> VariantVisitor<'de> for DeserializerEnumVisitor<'a, 'de> {
     fn visit_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value, Error>
         where V: Visitor<'de>
     {
-        match self.de.tokens.first() {
+        match self.de.tokens.peek() {
             Some(&Token::EnumSeqStart(_, _, enum_len)) => {
                 let token = self.de.next_token().unwrap();
 
@@ -510,7 +765,7 @@ impl<'de, 'a> VariantVisitor<'de> for DeserializerEnumVisitor<'a, 'de> {
     fn visit_struct<V>(self, fields: &'static [&'static str], visitor: V) -> Result<V::Value, Error>
         where V: Visitor<'de>
     {
-        match self.de.tokens.first() {
+        match self.de.tokens.peek() {
             Some(&Token::EnumMapStart(_, _, enum_len)) => {
                 let token = self.de.next_token().unwrap();
 
@@ -540,13 +795,17 @@ impl<'de, 'a> VariantVisitor<'de> for DeserializerEnumVisitor<'a, 'de> {
 
 //////////////////////////////////////////////////////////////////////////
 
-struct EnumMapVisitor<'a, 'de: 'a> {
-    de: &'a mut Deserializer<'de>,
+struct EnumMapVisitor<'a, 'de: 'a, S: 'a>
+    where S: TokenSource<'de>
+{
+    de: &'a mut Deserializer<'de, S>,
     variant: Option<&'a str>,
 }
 
-impl<'a, 'de> EnumMapVisitor<'a, 'de> {
-    fn new(de: &'a mut Deserializer<'de>, variant: &'a str) -> Self {
+impl<'a, 'de, S> EnumMapVisitor<'a, 'de, S>
+    where S: TokenSource<'de>
+{
+    fn new(de: &'a mut Deserializer<'de, S>, variant: &'a str) -> Self {
         EnumMapVisitor {
             de: de,
             variant: Some(variant),
@@ -554,7 +813,9 @@ impl<'a, 'de> EnumMapVisitor<'a, 'de> {
     }
 }
 
-impl<'de, 'a> MapVisitor<'de> for EnumMapVisitor<'a, 'de> {
+impl<'de, 'a, S> MapVisitor<'de> for EnumMapVisitor<'a, 'de, S>
+    where S: TokenSource<'de>
+{
     type Error = Error;
 
     fn visit_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Error>
@@ -569,7 +830,7 @@ impl<'de, 'a> MapVisitor<'de> for EnumMapVisitor<'a, 'de> {
     fn visit_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Error>
         where V: DeserializeSeed<'de>
     {
-        match self.de.tokens.first() {
+        match self.de.tokens.peek() {
             Some(&Token::EnumSeqSep) => {
                 let value = {
                     let visitor = DeserializerSeqVisitor {
@@ -0,0 +1,59 @@
+use std::error;
+use std::fmt::{self, Display};
+
+use serde::{de, ser};
+
+use token::Token;
+
+/// Error type returned from the `Serializer` and `Deserializer` in this
+/// crate.
+///
+/// When a test fails, the `Display` representation of this error is compared
+/// against the string passed to `assert_ser_tokens_error` /
+/// `assert_de_tokens_error`.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Error {
+    Message(String),
+    UnexpectedToken(Token),
+    EndOfTokens,
+    InvalidName(&'static str),
+    TrailingTokens(Token),
+}
+
+impl Display for Error {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Message(ref msg) => formatter.write_str(msg),
+            Error::UnexpectedToken(token) => write!(formatter, "unexpected token {:?}", token),
+            Error::EndOfTokens => formatter.write_str("end of tokens"),
+            Error::InvalidName(name) => write!(formatter, "invalid name `{}`", name),
+            Error::TrailingTokens(token) => write!(formatter, "trailing token {:?}", token),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        "Serde Test Error"
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error::Message(msg.to_string())
+    }
+}
+
+impl ser::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error::Message(msg.to_string())
+    }
+}
+
+/// Allows tests to compare an `Error` against the string they expect it to
+/// display as, without pattern matching on the enum.
+impl PartialEq<str> for Error {
+    fn eq(&self, other: &str) -> bool {
+        self.to_string() == other
+    }
+}
@@ -0,0 +1,46 @@
+//! This crate provides a convenient concise way to write unit tests for
+//! implementations of `Serialize` and `Deserialize`.
+//!
+//! The `Serialize` implementation for a value can be characterized by the
+//! sequence of `Serializer` calls that are made in the course of serializing
+//! the value, and the `Deserialize` implementation is similarly
+//! characterized by a sequence of `Deserializer` calls. Test cases in this
+//! crate check that the expected sequence of calls occurs as a result of
+//! serializing or deserializing the value in question.
+//!
+//! Without this crate, one could test serialization by comparing the
+//! serialized form of a value to an expected value, for example in JSON one
+//! might test that `json::to_string(&value)` produces the string
+//! `{"k":"v"}`. The trouble with this approach is that there are many
+//! equally valid representations of the same data in JSON as well as in
+//! other formats. It would be annoying to tie a test to one specific
+//! representation, as whitespace or field order might change for legitimate
+//! reasons in the course of development.
+//!
+//! Similarly one could test deserialization by comparing the result of
+//! deserializing some string to an expected value, but this does not test
+//! which specific sequence of Deserializer calls were made by the
+//! `Deserialize` implementation, which is what dictates which formats will
+//! successfully deserialize and which will not.
+//!
+//! This crate addresses the problem by checking the sequence of `Serializer`
+//! and `Deserializer` calls directly, agnostic of the data format.
+
+extern crate serde;
+
+mod error;
+mod token;
+
+pub mod de;
+pub mod ser;
+
+mod assert;
+
+pub use token::Token;
+pub use error::Error;
+pub use assert::{assert_tokens, assert_ser_tokens, assert_ser_tokens_error, assert_de_tokens,
+                  assert_de_tokens_error, assert_ser_tokens_error_matches,
+                  assert_de_tokens_error_matches, assert_tokens_eq_by, assert_de_tokens_eq_by,
+                  de_tokens_error};
+#[cfg(feature = "versioning")]
+pub use assert::{assert_tokens_versions, assert_ser_tokens_versions, assert_de_tokens_versions};
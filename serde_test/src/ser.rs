@@ -0,0 +1,346 @@
+use serde::ser::{self, Serialize};
+
+use error::Error;
+use token::Token;
+
+/// A `Serializer` that ensures that a value serializes to a given list of
+/// tokens.
+pub struct Serializer<'a> {
+    tokens: &'a [Token],
+    #[cfg(feature = "versioning")]
+    version_map: Option<&'a serde::de::VersionMap>,
+}
+
+impl<'a> Serializer<'a> {
+    /// Creates the serializer.
+    pub fn new(tokens: &'a [Token]) -> Self {
+        Serializer {
+            tokens: tokens,
+            #[cfg(feature = "versioning")]
+            version_map: None,
+        }
+    }
+
+    /// Creates the serializer, driving it with the given `version_map` so
+    /// that a `Serialize` impl can stamp the tokens it emits with the
+    /// current version number.
+    #[cfg(feature = "versioning")]
+    pub fn with_versions(tokens: &'a [Token], version_map: Option<&'a serde::de::VersionMap>) -> Self {
+        Serializer {
+            tokens: tokens,
+            version_map: version_map,
+        }
+    }
+
+    /// The version map this serializer was constructed with, if any.
+    #[cfg(feature = "versioning")]
+    pub fn version_map(&self) -> Option<&'a serde::de::VersionMap> {
+        self.version_map
+    }
+
+    /// Returns the number of tokens not yet consumed by serialization.
+    pub fn remaining(&self) -> usize {
+        self.tokens.len()
+    }
+
+    fn next_token(&mut self) -> Option<Token> {
+        if let Some((&first, rest)) = self.tokens.split_first() {
+            self.tokens = rest;
+            Some(first)
+        } else {
+            None
+        }
+    }
+
+    fn expect_token(&mut self, expected: Token) -> Result<(), Error> {
+        match self.next_token() {
+            Some(token) => {
+                if token == expected {
+                    Ok(())
+                } else {
+                    Err(Error::UnexpectedToken(token))
+                }
+            }
+            None => Err(Error::EndOfTokens),
+        }
+    }
+}
+
+macro_rules! serialize_primitive {
+    ($method:ident, $ty:ty, $token:ident) => {
+        fn $method(self, v: $ty) -> Result<(), Error> {
+            self.expect_token(Token::$token(v))
+        }
+    };
+}
+
+impl<'a, 'b> ser::Serializer for &'a mut Serializer<'b> {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Self;
+    type SerializeMap = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Self;
+
+    serialize_primitive!(serialize_bool, bool, Bool);
+    serialize_primitive!(serialize_i8, i8, I8);
+    serialize_primitive!(serialize_i16, i16, I16);
+    serialize_primitive!(serialize_i32, i32, I32);
+    serialize_primitive!(serialize_i64, i64, I64);
+    serialize_primitive!(serialize_u8, u8, U8);
+    serialize_primitive!(serialize_u16, u16, U16);
+    serialize_primitive!(serialize_u32, u32, U32);
+    serialize_primitive!(serialize_u64, u64, U64);
+    serialize_primitive!(serialize_f32, f32, F32);
+    serialize_primitive!(serialize_f64, f64, F64);
+    serialize_primitive!(serialize_char, char, Char);
+
+    #[cfg(feature = "versioning")]
+    fn version_map(&self) -> Option<&serde::de::VersionMap> {
+        self.version_map
+    }
+
+    fn serialize_str(self, v: &str) -> Result<(), Error> {
+        match self.next_token() {
+            Some(Token::Str(s)) | Some(Token::BorrowedStr(s)) | Some(Token::String(s))
+                if s == v => Ok(()),
+            Some(other) => Err(Error::UnexpectedToken(other)),
+            None => Err(Error::EndOfTokens),
+        }
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), Error> {
+        match self.next_token() {
+            Some(Token::Bytes(b)) | Some(Token::BorrowedBytes(b)) | Some(Token::ByteBuf(b))
+                if b == v => Ok(()),
+            Some(other) => Err(Error::UnexpectedToken(other)),
+            None => Err(Error::EndOfTokens),
+        }
+    }
+
+    fn serialize_none(self) -> Result<(), Error> {
+        self.expect_token(Token::Option(false))
+    }
+
+    fn serialize_some<T: ?Sized>(self, value: &T) -> Result<(), Error>
+        where T: Serialize
+    {
+        try!(self.expect_token(Token::Option(true)));
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), Error> {
+        self.expect_token(Token::Unit)
+    }
+
+    fn serialize_unit_struct(self, name: &'static str) -> Result<(), Error> {
+        self.expect_token(Token::UnitStruct(name))
+    }
+
+    fn serialize_unit_variant(self,
+                               name: &'static str,
+                               _variant_index: u32,
+                               variant: &'static str)
+                               -> Result<(), Error> {
+        self.expect_token(Token::EnumUnit(name, variant))
+    }
+
+    fn serialize_newtype_struct<T: ?Sized>(self,
+                                            name: &'static str,
+                                            value: &T)
+                                            -> Result<(), Error>
+        where T: Serialize
+    {
+        try!(self.expect_token(Token::StructNewType(name)));
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized>(self,
+                                             name: &'static str,
+                                             _variant_index: u32,
+                                             variant: &'static str,
+                                             value: &T)
+                                             -> Result<(), Error>
+        where T: Serialize
+    {
+        try!(self.expect_token(Token::EnumNewType(name, variant)));
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self, Error> {
+        try!(self.expect_token(Token::SeqStart(len)));
+        Ok(self)
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self, Error> {
+        try!(self.expect_token(Token::TupleStart(len)));
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(self,
+                               name: &'static str,
+                               len: usize)
+                               -> Result<Self, Error> {
+        try!(self.expect_token(Token::TupleStructStart(name, len)));
+        Ok(self)
+    }
+
+    fn serialize_tuple_variant(self,
+                                name: &'static str,
+                                _variant_index: u32,
+                                variant: &'static str,
+                                len: usize)
+                                -> Result<Self, Error> {
+        try!(self.expect_token(Token::EnumSeqStart(name, variant, len)));
+        Ok(self)
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self, Error> {
+        try!(self.expect_token(Token::MapStart(len)));
+        Ok(self)
+    }
+
+    fn serialize_struct(self, name: &'static str, len: usize) -> Result<Self, Error> {
+        try!(self.expect_token(Token::StructStart(name, len)));
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(self,
+                                 name: &'static str,
+                                 _variant_index: u32,
+                                 variant: &'static str,
+                                 len: usize)
+                                 -> Result<Self, Error> {
+        try!(self.expect_token(Token::EnumMapStart(name, variant, len)));
+        Ok(self)
+    }
+}
+
+impl<'a, 'b> ser::SerializeSeq for &'a mut Serializer<'b> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<(), Error>
+        where T: Serialize
+    {
+        try!(self.expect_token(Token::SeqSep));
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.expect_token(Token::SeqEnd)
+    }
+}
+
+impl<'a, 'b> ser::SerializeTuple for &'a mut Serializer<'b> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<(), Error>
+        where T: Serialize
+    {
+        try!(self.expect_token(Token::TupleSep));
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.expect_token(Token::TupleEnd)
+    }
+}
+
+impl<'a, 'b> ser::SerializeTupleStruct for &'a mut Serializer<'b> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<(), Error>
+        where T: Serialize
+    {
+        try!(self.expect_token(Token::TupleStructSep));
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.expect_token(Token::TupleStructEnd)
+    }
+}
+
+impl<'a, 'b> ser::SerializeTupleVariant for &'a mut Serializer<'b> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<(), Error>
+        where T: Serialize
+    {
+        try!(self.expect_token(Token::EnumSeqSep));
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.expect_token(Token::EnumSeqEnd)
+    }
+}
+
+impl<'a, 'b> ser::SerializeMap for &'a mut Serializer<'b> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T: ?Sized>(&mut self, key: &T) -> Result<(), Error>
+        where T: Serialize
+    {
+        try!(self.expect_token(Token::MapSep));
+        key.serialize(&mut **self)
+    }
+
+    fn serialize_value<T: ?Sized>(&mut self, value: &T) -> Result<(), Error>
+        where T: Serialize
+    {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.expect_token(Token::MapEnd)
+    }
+}
+
+impl<'a, 'b> ser::SerializeStruct for &'a mut Serializer<'b> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized>(&mut self,
+                                   key: &'static str,
+                                   value: &T)
+                                   -> Result<(), Error>
+        where T: Serialize
+    {
+        try!(self.expect_token(Token::StructSep));
+        try!(self.expect_token(Token::Str(key)));
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.expect_token(Token::StructEnd)
+    }
+}
+
+impl<'a, 'b> ser::SerializeStructVariant for &'a mut Serializer<'b> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized>(&mut self,
+                                   key: &'static str,
+                                   value: &T)
+                                   -> Result<(), Error>
+        where T: Serialize
+    {
+        try!(self.expect_token(Token::EnumMapSep));
+        try!(self.expect_token(Token::Str(key)));
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.expect_token(Token::EnumMapEnd)
+    }
+}
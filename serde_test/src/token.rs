@@ -0,0 +1,66 @@
+/// A token that can be used to represent a value in a data format, for use
+/// in testing serialization and deserialization.
+///
+/// Tokens are usually used in a list, see the [`Deserializer`](struct.Deserializer.html)
+/// and the various `assert_*` functions for how they are interpreted.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Token {
+    Bool(bool),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    F32(f32),
+    F64(f64),
+    /// A half-precision IEEE 754 float, stored as its raw bits to avoid
+    /// pulling a public `half` dependency into this enum.
+    F16Bits(u16),
+    Char(char),
+    Str(&'static str),
+    BorrowedStr(&'static str),
+    String(&'static str),
+    Bytes(&'static [u8]),
+    BorrowedBytes(&'static [u8]),
+    ByteBuf(&'static [u8]),
+
+    Option(bool),
+
+    Unit,
+    UnitStruct(&'static str),
+    StructNewType(&'static str),
+
+    SeqStart(Option<usize>),
+    SeqArrayStart(usize),
+    SeqSep,
+    SeqEnd,
+
+    TupleStart(usize),
+    TupleSep,
+    TupleEnd,
+
+    TupleStructStart(&'static str, usize),
+    TupleStructSep,
+    TupleStructEnd,
+
+    MapStart(Option<usize>),
+    MapSep,
+    MapEnd,
+
+    StructStart(&'static str, usize),
+    StructSep,
+    StructEnd,
+
+    EnumStart(&'static str),
+    EnumUnit(&'static str, &'static str),
+    EnumNewType(&'static str, &'static str),
+    EnumSeqStart(&'static str, &'static str, usize),
+    EnumSeqSep,
+    EnumSeqEnd,
+    EnumMapStart(&'static str, &'static str, usize),
+    EnumMapSep,
+    EnumMapEnd,
+}
@@ -0,0 +1,45 @@
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_test;
+
+use serde_test::{assert_de_tokens, assert_de_tokens_error, Token};
+
+#[derive(Deserialize, PartialEq, Debug)]
+struct Struct {
+    a: u8,
+    b: u8,
+}
+
+#[test]
+fn out_of_order_map_fields() {
+    assert_de_tokens(
+        &Struct { a: 1, b: 2 },
+        &[
+            Token::MapStart(Some(2)),
+            Token::MapSep,
+            Token::Str("b"),
+            Token::U8(2),
+            Token::MapSep,
+            Token::Str("a"),
+            Token::U8(1),
+            Token::MapEnd,
+        ],
+    );
+}
+
+#[test]
+fn duplicate_map_field_is_an_error() {
+    assert_de_tokens_error::<Struct>(
+        &[
+            Token::MapStart(Some(2)),
+            Token::MapSep,
+            Token::Str("a"),
+            Token::U8(1),
+            Token::MapSep,
+            Token::Str("a"),
+            Token::U8(9),
+        ],
+        "duplicate field `a`",
+    );
+}